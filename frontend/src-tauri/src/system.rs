@@ -1,70 +1,132 @@
 use anyhow::{Context, Result};
-use serde_json::json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tauri::path::BaseDirectory;
 use tauri::{AppHandle, Manager};
 use uuid::Uuid;
 
 const CLIENT_ID_FILENAME: &str = "client_id.txt";
+const ANALYTICS_ENABLED_FILENAME: &str = "analytics_enabled.txt";
+const ANALYTICS_QUEUE_FILENAME: &str = "analytics_queue.jsonl";
 const ANALYTICS_ENDPOINT: &str = "https://backend.valuecell.ai/api/v1/analytics/event";
+const ANALYTICS_PLATFORM: &str = "desktop";
+const LOG_TAIL_BYTES: usize = 16 * 1024;
+const PENDING_CRASH_FILENAME: &str = "pending_crash.json";
 
-/// Get or create a unique client ID.
-/// The client ID is persisted in the app data directory.
-/// Uses UUID v7 for generating a timestamp-based unique ID.
-pub async fn get_or_create_client_id(app: &AppHandle) -> Result<String> {
+const ANALYTICS_BATCH_SIZE: usize = 50;
+const ANALYTICS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const ANALYTICS_BACKOFF_BASE: Duration = Duration::from_secs(1);
+const ANALYTICS_BACKOFF_MAX: Duration = Duration::from_secs(180);
+
+static SESSION_ID: OnceLock<String> = OnceLock::new();
+
+/// Guards every append to and rewrite of `analytics_queue.jsonl`. The
+/// flusher takes it once to read a batch and again to drop that batch's
+/// bytes, but not across the network request, so an append from
+/// `enqueue_analytics_event` may land in between and is kept by the rewrite
+/// (both run in this same process, so a plain in-process mutex is
+/// sufficient).
+static ANALYTICS_QUEUE_LOCK: Mutex<()> = Mutex::new(());
+
+/// The ID for the current app launch, generated once and attached to every
+/// event so the backend can reconstruct sessions.
+fn session_id() -> &'static str {
+    SESSION_ID.get_or_init(|| Uuid::now_v7().to_string())
+}
+
+/// Where an analytics event actually goes once its payload has been built.
+///
+/// `Dry` is used when the user has opted out of telemetry: the event is
+/// still constructed and logged so the code path can be exercised and
+/// debugged, but it is never sent over the network.
+enum Transport {
+    Enqueue,
+    Dry,
+}
+
+/// A single analytics event as it is persisted to the offline queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QueuedEvent {
+    event_id: String,
+    event: String,
+    client_id: String,
+    os: String,
+    platform: String,
+    app_version: String,
+    session_id: String,
+    timestamp: u64,
+    #[serde(default)]
+    properties: Map<String, Value>,
+}
+
+fn now_unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn analytics_queue_path(app: &AppHandle) -> Result<PathBuf> {
     let app_data_dir = app
         .path()
         .resolve("", BaseDirectory::AppData)
         .context("Failed to resolve app data directory")?;
-    let client_id_path = app_data_dir.join(CLIENT_ID_FILENAME);
-
-    // Try to read existing client ID
-    if let Ok(content) = fs::read_to_string(&client_id_path) {
-        let client_id = content.trim().to_string();
-        if !client_id.is_empty() {
-            return Ok(client_id);
-        }
-    }
-
-    // Generate new unique client ID using UUID v7 (timestamp-based, ensures uniqueness across devices)
-    let client_id = Uuid::now_v7().to_string();
+    Ok(app_data_dir.join(ANALYTICS_QUEUE_FILENAME))
+}
 
-    // Ensure parent directory exists
-    if let Some(parent) = client_id_path.parent() {
+/// Append an event to the durable offline queue so it survives app restarts
+/// and periods without network connectivity. `spawn_analytics_flusher`
+/// drains this queue in the background. `properties` are caller-supplied
+/// and are stored under the event's `properties` key, alongside the
+/// standard envelope fields.
+fn enqueue_analytics_event(
+    app: &AppHandle,
+    event: &str,
+    client_id: &str,
+    properties: Map<String, Value>,
+) -> Result<()> {
+    let path = analytics_queue_path(app)?;
+    if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
             .with_context(|| format!("Failed to create directory: {:?}", parent))?;
     }
 
-    // Write client ID to file
-    fs::write(&client_id_path, &client_id)
-        .with_context(|| format!("Failed to write client ID to: {:?}", client_id_path))?;
+    let queued = QueuedEvent {
+        event_id: Uuid::now_v7().to_string(),
+        event: event.to_string(),
+        client_id: client_id.to_string(),
+        os: tauri_plugin_os::platform().to_string(),
+        platform: ANALYTICS_PLATFORM.to_string(),
+        app_version: app.package_info().version.to_string(),
+        session_id: session_id().to_string(),
+        timestamp: now_unix_millis(),
+        properties,
+    };
+    let line =
+        serde_json::to_string(&queued).context("Failed to serialize queued analytics event")?;
 
-    // Send analytics event to server when creating new client_id (fire and forget)
-    let client_id_clone = client_id.clone();
-    tauri::async_runtime::spawn(async move {
-        if let Err(e) = send_analytics_event(&client_id_clone).await {
-            log::warn!(
-                "Failed to send analytics event to {}: {e:#}",
-                ANALYTICS_ENDPOINT
-            );
-        }
-    });
+    let _guard = ANALYTICS_QUEUE_LOCK.lock().unwrap();
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open analytics queue: {:?}", path))?;
+    writeln!(file, "{line}")
+        .with_context(|| format!("Failed to append to analytics queue: {:?}", path))?;
 
-    Ok(client_id)
+    Ok(())
 }
 
-/// Send analytics event to server with client_id and OS information
-async fn send_analytics_event(client_id: &str) -> Result<()> {
-    let payload = json!({
-        "event": "init",
-        "client_id": client_id,
-        "os": tauri_plugin_os::platform()
-    });
-
-    let client = reqwest::Client::new();
+/// POST a batch of queued events to the analytics endpoint.
+async fn send_analytics_batch(client: &reqwest::Client, events: &[QueuedEvent]) -> Result<()> {
     client
         .post(ANALYTICS_ENDPOINT)
-        .json(&payload)
+        .json(&json!({ "events": events }))
         .send()
         .await
         .context("Failed to send HTTP request")?
@@ -74,9 +136,428 @@ async fn send_analytics_event(client_id: &str) -> Result<()> {
     Ok(())
 }
 
+/// Try to drain up to `ANALYTICS_BATCH_SIZE` pending events from the queue.
+///
+/// Returns `Ok(true)` if progress was made (a batch was sent, or only
+/// unparseable lines were dropped) and those lines were removed from the
+/// queue file, `Ok(false)` if the queue was empty, and `Err` if delivery
+/// failed (the lines are left untouched so the next attempt can retry
+/// them). `ANALYTICS_QUEUE_LOCK` is held while reading the batch and again
+/// while dropping its bytes (but not across the network request), so a
+/// concurrent `enqueue_analytics_event` can never land in between and get
+/// silently dropped.
+async fn try_flush_analytics_batch(app: &AppHandle, client: &reqwest::Client) -> Result<bool> {
+    let path = analytics_queue_path(app)?;
+
+    let batch_lines: Vec<String> = {
+        let _guard = ANALYTICS_QUEUE_LOCK.lock().unwrap();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return Ok(false),
+        };
+        content
+            .lines()
+            .take(ANALYTICS_BATCH_SIZE)
+            .map(|l| l.to_string())
+            .collect()
+    };
+    if batch_lines.is_empty() {
+        return Ok(false);
+    }
+
+    // Bytes consumed by the batch, including each line's trailing newline
+    // (written via `writeln!`), so we know exactly how much of the file to
+    // drop once it's dealt with.
+    let consumed_bytes: usize = batch_lines.iter().map(|l| l.len() + 1).sum();
+
+    let mut events = Vec::with_capacity(batch_lines.len());
+    for line in &batch_lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<QueuedEvent>(line) {
+            Ok(event) => events.push(event),
+            Err(e) => log::warn!("Dropping unparseable analytics queue line: {e:#}"),
+        }
+    }
+
+    if !events.is_empty() {
+        send_analytics_batch(client, &events).await?;
+    }
+
+    // Drop exactly the bytes we just consumed. Everything after them was
+    // appended under the same lock, so re-reading here under the lock can
+    // only see the prefix we sent plus whatever was appended since.
+    let _guard = ANALYTICS_QUEUE_LOCK.lock().unwrap();
+    let current = fs::read_to_string(&path).unwrap_or_default();
+    let remaining = current.get(consumed_bytes..).unwrap_or("");
+    fs::write(&path, remaining)
+        .with_context(|| format!("Failed to rewrite analytics queue: {:?}", path))?;
+
+    Ok(true)
+}
+
+/// Background task that continuously drains the offline analytics queue.
+///
+/// Failed batches are retried with exponential backoff (1s, 2s, 4s, …,
+/// capped at a few minutes); the queue lives on disk, so restarting the app
+/// simply resumes draining whatever was left behind. Draining pauses
+/// entirely while the user has opted out, so opting out actually stops
+/// outbound network traffic rather than just the `init`/`track_event`
+/// enqueue calls.
+pub fn spawn_analytics_flusher(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut backoff = ANALYTICS_BACKOFF_BASE;
+        loop {
+            if !is_analytics_enabled(&app).unwrap_or(true) {
+                tokio::time::sleep(ANALYTICS_POLL_INTERVAL).await;
+                continue;
+            }
+
+            match try_flush_analytics_batch(&app, &client).await {
+                Ok(true) => {
+                    backoff = ANALYTICS_BACKOFF_BASE;
+                }
+                Ok(false) => {
+                    backoff = ANALYTICS_BACKOFF_BASE;
+                    tokio::time::sleep(ANALYTICS_POLL_INTERVAL).await;
+                }
+                Err(e) => {
+                    log::warn!("Failed to flush analytics queue, retrying in {backoff:?}: {e:#}");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(ANALYTICS_BACKOFF_MAX);
+                }
+            }
+        }
+    });
+}
+
+/// Resolve the path to the persisted analytics opt-in flag.
+fn analytics_enabled_path(app: &AppHandle) -> Result<PathBuf> {
+    let app_data_dir = app
+        .path()
+        .resolve("", BaseDirectory::AppData)
+        .context("Failed to resolve app data directory")?;
+    Ok(app_data_dir.join(ANALYTICS_ENABLED_FILENAME))
+}
+
+/// Whether the user has enabled telemetry. Defaults to enabled when no
+/// preference has been recorded yet.
+pub fn is_analytics_enabled(app: &AppHandle) -> Result<bool> {
+    let path = analytics_enabled_path(app)?;
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(content.trim() != "false"),
+        Err(_) => Ok(true),
+    }
+}
+
+/// Persist the user's telemetry preference.
+pub fn set_analytics_enabled(app: &AppHandle, enabled: bool) -> Result<()> {
+    let path = analytics_enabled_path(app)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+    }
+    fs::write(&path, if enabled { "true" } else { "false" })
+        .with_context(|| format!("Failed to write analytics preference to: {:?}", path))?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_analytics_enabled(app: tauri::AppHandle) -> Result<bool, String> {
+    is_analytics_enabled(&app).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn set_analytics_enabled_command(app: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    set_analytics_enabled(&app, enabled).map_err(|e| e.to_string())
+}
+
+/// A client ID together with whether it was just generated.
+///
+/// `is_first_run` is `true` only on the install that generates a fresh
+/// UUID v7 and writes it to disk, letting the frontend drive an
+/// onboarding/welcome flow for brand-new installs.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientId {
+    pub id: String,
+    pub is_first_run: bool,
+}
+
+/// Get or create a unique client ID.
+/// The client ID is persisted in the app data directory.
+/// Uses UUID v7 for generating a timestamp-based unique ID.
+pub async fn get_or_create_client_id(app: &AppHandle) -> Result<ClientId> {
+    let app_data_dir = app
+        .path()
+        .resolve("", BaseDirectory::AppData)
+        .context("Failed to resolve app data directory")?;
+    let client_id_path = app_data_dir.join(CLIENT_ID_FILENAME);
+
+    // Try to read existing client ID
+    let existing = fs::read_to_string(&client_id_path)
+        .ok()
+        .map(|content| content.trim().to_string())
+        .filter(|content| !content.is_empty());
+
+    let (client_id, is_first_run) = if let Some(client_id) = existing {
+        (client_id, false)
+    } else {
+        // Generate new unique client ID using UUID v7 (timestamp-based, ensures uniqueness across devices)
+        let client_id = Uuid::now_v7().to_string();
+
+        // Ensure parent directory exists
+        if let Some(parent) = client_id_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+        }
+
+        // Write client ID to file
+        fs::write(&client_id_path, &client_id)
+            .with_context(|| format!("Failed to write client ID to: {:?}", client_id_path))?;
+
+        // Enqueue the init analytics event for the background flusher to deliver,
+        // unless the user has opted out of telemetry entirely.
+        if is_analytics_enabled(app)? {
+            record_analytics_event(app, "init", &client_id, Map::new(), Transport::Enqueue)?;
+        }
+
+        (client_id, true)
+    };
+
+    // Pick up and deliver any crash report the panic hook left behind on a
+    // previous run, now that the client ID is known. A report left behind
+    // while the user is opted out is simply left in place (not sent, not
+    // discarded) in case telemetry is re-enabled later.
+    if is_analytics_enabled(app)? {
+        let http_client = reqwest::Client::new();
+        if let Err(e) = upload_pending_crash(app, &http_client, &client_id).await {
+            log::warn!("Failed to upload pending crash report, will retry next launch: {e:#}");
+        }
+    }
+
+    Ok(ClientId {
+        id: client_id,
+        is_first_run,
+    })
+}
+
+/// Record an analytics event for `client_id`.
+///
+/// When `transport` is `Transport::Dry` the payload is still built and
+/// logged locally, but it is never enqueued for delivery. Otherwise the
+/// event is durably queued and left for `spawn_analytics_flusher` to send.
+fn record_analytics_event(
+    app: &AppHandle,
+    event: &str,
+    client_id: &str,
+    properties: Map<String, Value>,
+    transport: Transport,
+) -> Result<()> {
+    match transport {
+        Transport::Dry => {
+            // Build the same envelope `enqueue_analytics_event` would have
+            // persisted, so the logged event matches what would have been
+            // sent had the user not opted out.
+            let payload = QueuedEvent {
+                event_id: Uuid::now_v7().to_string(),
+                event: event.to_string(),
+                client_id: client_id.to_string(),
+                os: tauri_plugin_os::platform().to_string(),
+                platform: ANALYTICS_PLATFORM.to_string(),
+                app_version: app.package_info().version.to_string(),
+                session_id: session_id().to_string(),
+                timestamp: now_unix_millis(),
+                properties,
+            };
+            log::info!("Analytics (dry-run, not sent): {}", json!(payload));
+            Ok(())
+        }
+        Transport::Enqueue => enqueue_analytics_event(app, event, client_id, properties),
+    }
+}
+
 #[tauri::command]
-pub async fn get_client_id(app: tauri::AppHandle) -> Result<String, String> {
+pub async fn get_client_id(app: tauri::AppHandle) -> Result<ClientId, String> {
     get_or_create_client_id(&app)
         .await
         .map_err(|e| e.to_string())
 }
+
+/// Record an arbitrary analytics event with caller-supplied properties.
+///
+/// This is the general entry point the frontend should use for feature
+/// usage, screen views, and other events beyond the implicit `init` ping;
+/// `properties` are sent under the event's `properties` key together with
+/// the standard envelope (client_id, os, platform, app version, session_id).
+/// The event is queued for delivery, or only logged when the user has opted
+/// out of telemetry.
+#[tauri::command]
+pub async fn track_event(
+    app: tauri::AppHandle,
+    event: String,
+    properties: Map<String, Value>,
+) -> Result<(), String> {
+    track_event_internal(&app, &event, properties)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn track_event_internal(
+    app: &AppHandle,
+    event: &str,
+    properties: Map<String, Value>,
+) -> Result<()> {
+    let client_id = get_or_create_client_id(app).await?.id;
+    let transport = if is_analytics_enabled(app)? {
+        Transport::Enqueue
+    } else {
+        Transport::Dry
+    };
+
+    record_analytics_event(app, event, &client_id, properties, transport)
+}
+
+/// Find the most recently modified file in the app's log directory.
+fn find_last_log_file(app: &AppHandle) -> Result<PathBuf> {
+    let log_dir = app
+        .path()
+        .resolve("", BaseDirectory::AppLog)
+        .context("Failed to resolve app log directory")?;
+    let entries = fs::read_dir(&log_dir)
+        .with_context(|| format!("Failed to read log directory: {:?}", log_dir))?;
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .max_by_key(|entry| entry.metadata().and_then(|m| m.modified()).ok())
+        .map(|entry| entry.path())
+        .with_context(|| format!("No log files found in: {:?}", log_dir))
+}
+
+/// Read the most recent log file, truncated to its last `LOG_TAIL_BYTES`
+/// bytes so it is cheap to attach to a crash or bug report.
+pub fn read_last_log_tail(app: &AppHandle) -> Result<String> {
+    let path = find_last_log_file(app)?;
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read log file: {:?}", path))?;
+    let tail_start = content.len().saturating_sub(LOG_TAIL_BYTES);
+    let tail_start = (tail_start..=content.len())
+        .find(|&i| content.is_char_boundary(i))
+        .unwrap_or(content.len());
+    Ok(content[tail_start..].to_string())
+}
+
+#[tauri::command]
+pub fn get_last_log_file(app: tauri::AppHandle) -> Result<String, String> {
+    read_last_log_tail(&app).map_err(|e| e.to_string())
+}
+
+/// A crash report captured by the panic hook, written synchronously so it
+/// survives the process exiting right after the panic.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CrashReport {
+    client_id: String,
+    os: String,
+    platform: String,
+    app_version: String,
+    message: String,
+    backtrace: String,
+    timestamp: u64,
+}
+
+fn pending_crash_path(app: &AppHandle) -> Result<PathBuf> {
+    let app_data_dir = app
+        .path()
+        .resolve("", BaseDirectory::AppData)
+        .context("Failed to resolve app data directory")?;
+    Ok(app_data_dir.join(PENDING_CRASH_FILENAME))
+}
+
+/// Install a panic hook that captures unhandled panics into a crash report.
+///
+/// The report is written to `pending_crash.json` synchronously inside the
+/// hook, since spawning async work from a panicking thread isn't safe; it is
+/// picked up and delivered as a `crash` event by `upload_pending_crash` on
+/// the next launch, keyed to the same client ID used for analytics.
+pub fn install_panic_hook(app: AppHandle, client_id: String) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic payload".to_string());
+        let location = info
+            .location()
+            .map(|l| l.to_string())
+            .unwrap_or_else(|| "unknown location".to_string());
+
+        let report = CrashReport {
+            client_id: client_id.clone(),
+            os: tauri_plugin_os::platform().to_string(),
+            platform: ANALYTICS_PLATFORM.to_string(),
+            app_version: app.package_info().version.to_string(),
+            message: format!("{message} ({location})"),
+            backtrace: std::backtrace::Backtrace::force_capture().to_string(),
+            timestamp: now_unix_millis(),
+        };
+
+        let Ok(path) = pending_crash_path(&app) else {
+            return;
+        };
+        if let Ok(json) = serde_json::to_string(&report) {
+            let _ = fs::write(&path, json);
+        }
+    }));
+}
+
+/// If a previous run's panic hook left a crash report behind, deliver it as
+/// a `crash` event and delete the file only once the POST actually
+/// succeeds, giving at-least-once delivery. If delivery fails (e.g.
+/// offline), the file is left in place so the next launch retries it.
+async fn upload_pending_crash(
+    app: &AppHandle,
+    client: &reqwest::Client,
+    client_id: &str,
+) -> Result<()> {
+    let path = pending_crash_path(app)?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => return Ok(()),
+    };
+
+    let report: CrashReport = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse pending crash report: {:?}", path))?;
+
+    let mut properties = Map::new();
+    properties.insert("message".to_string(), json!(report.message));
+    properties.insert("backtrace".to_string(), json!(report.backtrace));
+    properties.insert(
+        "log_tail".to_string(),
+        json!(read_last_log_tail(app).unwrap_or_default()),
+    );
+
+    let queued = QueuedEvent {
+        event_id: Uuid::now_v7().to_string(),
+        event: "crash".to_string(),
+        client_id: client_id.to_string(),
+        os: report.os,
+        platform: report.platform,
+        app_version: report.app_version,
+        session_id: session_id().to_string(),
+        timestamp: report.timestamp,
+        properties,
+    };
+
+    send_analytics_batch(client, &[queued]).await?;
+
+    fs::remove_file(&path)
+        .with_context(|| format!("Failed to remove pending crash report: {:?}", path))?;
+
+    Ok(())
+}